@@ -14,14 +14,34 @@ extern crate log;
 #[macro_use]
 extern crate macro_attr;
 
+extern crate directories;
+extern crate interprocess;
+extern crate mccs;
+extern crate mccs_caps;
+extern crate mccs_db;
+extern crate sled;
+
 use clap::{App, AppSettings, Arg, SubCommand};
 use conv::TryFrom;
-use ddc_hi::{Backend, Ddc, DdcHost, Display, Query};
+use ddc_hi::{Backend, Ddc, DdcHost, DdcTable, Display, Query};
+use directories::ProjectDirs;
 use failure::Error;
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use mccs_db::{Access, Descriptor, ValueType};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const INPUT_SELECT: u8 = 0x60;
 
+/// Default capability cache lifetime (7 days) before an entry is re-probed.
+const DEFAULT_CACHE_TTL: u64 = 7 * 24 * 60 * 60;
+
 macro_attr! {
     #[derive(Clone, Copy, Debug, PartialEq, EnumDisplay!, EnumFromStr!, IterVariantNames!(InputSourceVariantNames), TryFrom!(u16))]
     #[repr(u8)]
@@ -47,6 +67,53 @@ macro_attr! {
     }
 }
 
+/// An input source value, either one of the named MCCS variants or a raw code
+/// for the USB-C/Thunderbolt and vendor-specific inputs that fall outside the
+/// 18 standard names (e.g. `0x1b`, `0x1f`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InputValue {
+    Named(InputSource),
+    Raw(u16),
+}
+
+impl InputValue {
+    /// The raw VCP value to write for this input.
+    fn value(self) -> u16 {
+        match self {
+            InputValue::Named(source) => source as u16,
+            InputValue::Raw(value) => value,
+        }
+    }
+
+    /// Wrap a raw readback, preferring a named variant when the code is known.
+    fn from_raw(value: u16) -> InputValue {
+        match InputSource::try_from(value) {
+            Ok(source) => InputValue::Named(source),
+            Err(_) => InputValue::Raw(value),
+        }
+    }
+}
+
+impl FromStr for InputValue {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<InputValue, Error> {
+        match InputSource::from_str(s) {
+            Ok(source) => Ok(InputValue::Named(source)),
+            Err(_) => Ok(InputValue::from_raw(parse_int(s)?)),
+        }
+    }
+}
+
+impl fmt::Display for InputValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputValue::Named(source) => write!(f, "{}", source),
+            InputValue::Raw(value) => write!(f, "0x{:02x}", value),
+        }
+    }
+}
+
 #[derive(Default)]
 struct DisplaySleep(Vec<Display>);
 
@@ -65,7 +132,7 @@ impl Drop for DisplaySleep {
     }
 }
 
-fn displays(query: (Query, bool)) -> Result<Vec<Display>, Error> {
+fn displays(query: (&Query, bool)) -> Result<Vec<Display>, Error> {
     let needs_caps = query.1;
     let query = query.0;
     Display::enumerate()
@@ -85,25 +152,611 @@ fn displays(query: (Query, bool)) -> Result<Vec<Display>, Error> {
         }).collect()
 }
 
-fn set_input_source(display: &mut Display, input_source: InputSource) -> Result<(), Error> {
+fn set_input_source(display: &mut Display, input_source: InputValue) -> Result<(), Error> {
     if let Some(feature) = display.info.mccs_database.get(INPUT_SELECT) {
         display
             .handle
-            .set_vcp_feature(feature.code, input_source as u16)
+            .set_vcp_feature(feature.code, input_source.value())
     } else {
         Err(format_err!("Could not access input source feature"))
     }
 }
 
-fn get_input_source(display: &mut Display) -> Result<InputSource, Error> {
+/// Given a display's current input, return the next one in an ordered cycle,
+/// wrapping around. Inputs not present in the list start the cycle from the
+/// beginning. Comparison is by raw value so named and raw forms match.
+fn next_in_cycle(current: InputValue, sources: &[InputValue]) -> InputValue {
+    match sources.iter().position(|s| s.value() == current.value()) {
+        Some(index) => sources[(index + 1) % sources.len()],
+        None => sources[0],
+    }
+}
+
+fn get_input_source(display: &mut Display) -> Result<InputValue, Error> {
     if let Some(feature) = display.info.mccs_database.get(INPUT_SELECT) {
-        InputSource::try_from(display.handle.get_vcp_feature(feature.code)?.value())
-            .map_err(Error::from)
+        Ok(InputValue::from_raw(
+            display.handle.get_vcp_feature(feature.code)?.value(),
+        ))
     } else {
         Err(format_err!("Could not access input source feature"))
     }
 }
 
+/// A display's raw capability string as persisted in the on-disk cache; it is
+/// parsed again each time it is applied.
+struct CacheEntry {
+    capabilities: String,
+}
+
+/// Small embedded key-value store persisting each display's capability string
+/// so the slow DDC/CI capabilities request can be skipped on later runs. Keyed
+/// by a stable display identity (backend + manufacturer + model + serial).
+struct Cache {
+    db: sled::Db,
+    ttl: Option<u64>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Cache {
+    fn open(ttl: Option<u64>) -> Result<Cache, Error> {
+        let dirs = ProjectDirs::from("", "", "monitor-switch")
+            .ok_or_else(|| format_err!("Could not determine a cache directory"))?;
+        let path: PathBuf = dirs.cache_dir().join("capabilities");
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(Cache {
+            db: sled::open(path)?,
+            ttl,
+        })
+    }
+
+    fn get(&self, identity: &str) -> Option<CacheEntry> {
+        let raw = self.db.get(identity).ok().and_then(|v| v)?;
+        let text = String::from_utf8(raw.to_vec()).ok()?;
+        let mut lines = text.splitn(2, '\n');
+        let stored: u64 = lines.next()?.parse().ok()?;
+        if let Some(ttl) = self.ttl {
+            if unix_now().saturating_sub(stored) > ttl {
+                return None;
+            }
+        }
+        let capabilities = lines.next()?.to_owned();
+        Some(CacheEntry { capabilities })
+    }
+
+    fn put(&self, identity: &str, entry: &CacheEntry) -> Result<(), Error> {
+        let value = format!("{}\n{}", unix_now(), entry.capabilities);
+        self.db.insert(identity, value.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// Stable identity used as the cache key, derived from EDID-level metadata that
+/// is available before the capabilities request.
+fn display_identity(display: &Display) -> String {
+    let info = &display.info;
+    format!(
+        "{}:{}:{}:{}",
+        info.backend,
+        info.manufacturer_id.as_deref().unwrap_or(""),
+        info.model_name.as_deref().unwrap_or(""),
+        info.serial_number.as_deref().unwrap_or(""),
+    )
+}
+
+/// Parse a raw capability string into the display's `mccs_database`, mirroring
+/// what `Display::update_capabilities` does internally.
+fn apply_capabilities(display: &mut Display, caps: &[u8]) -> Result<(), Error> {
+    let caps = mccs_caps::parse_capabilities(caps)?;
+    let mut database = mccs_db::Database::from_version(
+        caps.mccs_version.as_ref().unwrap_or(&mccs::Version::default()),
+    );
+    database.apply_capabilities(&caps);
+    display.info.mccs_database = database;
+    Ok(())
+}
+
+/// Populate a display's capabilities, preferring the cache and only issuing the
+/// slow DDC/CI capabilities request on a miss, a TTL expiry, or `--refresh-cache`.
+fn ensure_capabilities(
+    display: &mut Display,
+    cache: Option<&Cache>,
+    refresh: bool,
+) -> Result<(), Error> {
+    let identity = display_identity(display);
+
+    if !refresh {
+        if let Some(cache) = cache {
+            if let Some(entry) = cache.get(&identity) {
+                debug!("Using cached capabilities for {}", identity);
+                return apply_capabilities(display, entry.capabilities.as_bytes());
+            }
+        }
+    }
+
+    let caps = display.handle.capabilities_string()?;
+    apply_capabilities(display, &caps)?;
+
+    if let Some(cache) = cache {
+        let entry = CacheEntry {
+            capabilities: String::from_utf8_lossy(&caps).into_owned(),
+        };
+        if let Err(e) = cache.put(&identity, &entry) {
+            warn!("Could not persist capability cache for {}: {}", identity, e)
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a VCP feature code or raw feature value, accepting either a decimal
+/// (`16`) or hex (`0x10`) literal.
+fn parse_int(s: &str) -> Result<u16, Error> {
+    let s = s.trim();
+    let value = if s.starts_with("0x") || s.starts_with("0X") {
+        u16::from_str_radix(&s[2..], 16)
+    } else {
+        s.parse::<u16>()
+    };
+    value.map_err(|e| format_err!("Invalid value '{}': {}", s, e))
+}
+
+fn parse_feature_code(s: &str) -> Result<u8, Error> {
+    let value = parse_int(s)?;
+    if value > u16::from(u8::MAX) {
+        bail!("Feature code '{}' does not fit in a byte", s)
+    }
+    Ok(value as u8)
+}
+
+/// Human-readable label for a feature code, preferring the database name and
+/// falling back to the raw code.
+fn feature_label(code: u8, descriptor: Option<&Descriptor>) -> String {
+    match descriptor.and_then(|d| d.name.as_ref()) {
+        Some(name) => format!("0x{:02x} ({})", code, name),
+        None => format!("0x{:02x}", code),
+    }
+}
+
+/// Resolve a `setvcp` value argument against a feature's database entry. A
+/// non-continuous feature accepts one of its named values (case insensitive);
+/// any feature accepts a raw decimal or hex literal.
+fn parse_feature_value(value: &str, descriptor: Option<&Descriptor>) -> Result<u16, Error> {
+    if let Some(descriptor) = descriptor {
+        if let ValueType::NonContinuous { ref values, .. } = descriptor.ty {
+            for (raw, name) in values {
+                if let Some(name) = name {
+                    if name.eq_ignore_ascii_case(value) {
+                        return Ok(u16::from(*raw));
+                    }
+                }
+            }
+        }
+    }
+    parse_int(value)
+}
+
+/// Parse a whitespace- or comma-separated list of hex bytes for a table write.
+fn parse_table_value(value: &str) -> Result<Vec<u8>, Error> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let s = if s.starts_with("0x") || s.starts_with("0X") {
+                &s[2..]
+            } else {
+                s
+            };
+            u8::from_str_radix(s, 16).map_err(|e| format_err!("Invalid table byte '{}': {}", s, e))
+        }).collect()
+}
+
+fn getvcp(display: &mut Display, code: u8, out: &mut dyn Write) -> Result<(), Error> {
+    let descriptor = display.info.mccs_database.get(code).cloned();
+    let label = feature_label(code, descriptor.as_ref());
+
+    if let Some(ref descriptor) = descriptor {
+        if descriptor.access == Access::WriteOnly {
+            warn!("Feature {} is write-only, reading may return garbage", label);
+        }
+        if let ValueType::Table { .. } = descriptor.ty {
+            let table = display.handle.table_read(code)?;
+            let hex: Vec<_> = table.iter().map(|b| format!("{:02x}", b)).collect();
+            writeln!(out, "{}: {} = [{}]", display.info.id, label, hex.join(" "))?;
+            return Ok(());
+        }
+    }
+
+    let value = display.handle.get_vcp_feature(code)?;
+    let rendered = match descriptor.as_ref().map(|d| &d.ty) {
+        Some(ValueType::Continuous { .. }) | None => {
+            format!("{} / {}", value.value(), value.maximum())
+        }
+        Some(ValueType::NonContinuous { ref values, .. }) => {
+            let raw = value.value() as u8;
+            match values.get(&raw) {
+                Some(Some(name)) => name.clone(),
+                _ => format!("0x{:02x}", raw),
+            }
+        }
+        Some(ValueType::Table { .. }) => unreachable!("handled above"),
+        Some(ValueType::Unknown) => format!("0x{:04x}", value.value()),
+    };
+    writeln!(out, "{}: {} = {}", display.info.id, label, rendered)?;
+    Ok(())
+}
+
+fn setvcp(display: &mut Display, code: u8, value: &str) -> Result<(), Error> {
+    let descriptor = display.info.mccs_database.get(code).cloned();
+    let label = feature_label(code, descriptor.as_ref());
+
+    if let Some(ref descriptor) = descriptor {
+        if descriptor.access == Access::ReadOnly {
+            bail!("Feature {} is read-only", label)
+        }
+        if let ValueType::Table { .. } = descriptor.ty {
+            let table = parse_table_value(value)?;
+            return display.handle.table_write(code, 0, &table);
+        }
+    }
+
+    let raw = parse_feature_value(value, descriptor.as_ref())?;
+    display.handle.set_vcp_feature(code, raw)
+}
+
+/// A control command, shared by the CLI entrypoint and the control socket so
+/// both dispatch through the exact same handler.
+enum Command {
+    Set(InputValue),
+    Toggle(InputValue, InputValue),
+    Cycle(Vec<InputValue>, bool),
+    GetVcp(u8),
+    SetVcp(u8, String),
+    List,
+}
+
+/// Parse a line-framed control command, e.g. `set Hdmi1` or `getvcp 0x10`.
+fn parse_command_line(line: &str) -> Result<Command, Error> {
+    let mut parts = line.split_whitespace();
+    let verb = parts.next().ok_or_else(|| format_err!("Empty command"))?;
+    match verb {
+        "set" => {
+            let input = parts.next().ok_or_else(|| format_err!("set requires an input"))?;
+            Ok(Command::Set(InputValue::from_str(input)?))
+        }
+        "toggle" => {
+            let a = parts.next().ok_or_else(|| format_err!("toggle requires two inputs"))?;
+            let b = parts.next().ok_or_else(|| format_err!("toggle requires two inputs"))?;
+            Ok(Command::Toggle(
+                InputValue::from_str(a)?,
+                InputValue::from_str(b)?,
+            ))
+        }
+        "getvcp" => {
+            let code = parts.next().ok_or_else(|| format_err!("getvcp requires a code"))?;
+            Ok(Command::GetVcp(parse_feature_code(code)?))
+        }
+        "setvcp" => {
+            let code = parts.next().ok_or_else(|| format_err!("setvcp requires a code"))?;
+            let value = parts.next().ok_or_else(|| format_err!("setvcp requires a value"))?;
+            Ok(Command::SetVcp(parse_feature_code(code)?, value.to_owned()))
+        }
+        "cycle" => {
+            let sources = parts
+                .map(InputValue::from_str)
+                .collect::<Result<Vec<_>, _>>()?;
+            if sources.len() < 2 {
+                bail!("cycle requires at least two inputs")
+            }
+            Ok(Command::Cycle(sources, false))
+        }
+        "list" => Ok(Command::List),
+        other => bail!("Unknown command '{}'", other),
+    }
+}
+
+/// Execute a command against every matching display, writing results to `out`.
+/// This is the single dispatch point behind both the CLI and the control socket.
+/// `bus_lock` is held for the whole call so a daemon's poll loop and a
+/// concurrent control connection never issue overlapping DDC/CI transactions.
+fn run_command(
+    command: &Command,
+    query: &Query,
+    needs_caps: bool,
+    cache: Option<&Cache>,
+    refresh: bool,
+    bus_lock: &Mutex<()>,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let _guard = bus_lock.lock().unwrap();
+    let mut sleep = DisplaySleep::default();
+
+    match command {
+        Command::List => {
+            for display in displays((query, needs_caps))? {
+                writeln!(out, "{}", display_identity(&display))?;
+                sleep.add(display);
+            }
+        }
+        Command::Set(input_source) => {
+            for mut display in displays((query, needs_caps))? {
+                ensure_capabilities(&mut display, cache, refresh)?;
+                // This sometimes fails but the switch still succeeded, ignore the Err for now
+                if let Err(e) = set_input_source(&mut display, *input_source) {
+                    warn!("Error while setting input: {}", e)
+                } else {
+                    writeln!(out, "{}: set {}", display.info.id, input_source)?;
+                }
+                sleep.add(display);
+            }
+        }
+        Command::Toggle(input_source_1, input_source_2) => {
+            let mut target: Option<InputValue> = None;
+            for mut display in displays((query, needs_caps))? {
+                ensure_capabilities(&mut display, cache, refresh)?;
+
+                if target.is_none() {
+                    let current = get_input_source(&mut display)?;
+
+                    target = if current == *input_source_1 {
+                        Some(*input_source_2)
+                    } else if current == *input_source_2 {
+                        Some(*input_source_1)
+                    } else {
+                        bail!(format_err!("Current input source is not a toggle option"))
+                    }
+                }
+
+                if let Some(input_source) = target {
+                    // This sometimes fails but the switch still succeeded, ignore the Err for now
+                    if let Err(e) = set_input_source(&mut display, input_source) {
+                        warn!("Error while setting input: {}", e)
+                    } else {
+                        writeln!(out, "{}: set {}", display.info.id, input_source)?;
+                    }
+                }
+
+                sleep.add(display);
+            }
+        }
+        Command::Cycle(sources, synchronized) => {
+            // In synchronized mode the next input is decided once from the first
+            // display and applied to all; otherwise each display advances from
+            // its own current input.
+            let mut shared: Option<InputValue> = None;
+            for mut display in displays((query, needs_caps))? {
+                ensure_capabilities(&mut display, cache, refresh)?;
+
+                let target = if *synchronized {
+                    if shared.is_none() {
+                        let current = get_input_source(&mut display)?;
+                        shared = Some(next_in_cycle(current, sources));
+                    }
+                    shared.unwrap()
+                } else {
+                    let current = get_input_source(&mut display)?;
+                    next_in_cycle(current, sources)
+                };
+
+                // This sometimes fails but the switch still succeeded, ignore the Err for now
+                if let Err(e) = set_input_source(&mut display, target) {
+                    warn!("Error while setting input: {}", e)
+                } else {
+                    writeln!(out, "{}: set {}", display.info.id, target)?;
+                }
+                sleep.add(display);
+            }
+        }
+        Command::GetVcp(code) => {
+            for mut display in displays((query, needs_caps))? {
+                ensure_capabilities(&mut display, cache, refresh)?;
+                if let Err(e) = getvcp(&mut display, *code, out) {
+                    warn!("Error while reading feature: {}", e)
+                }
+                sleep.add(display);
+            }
+        }
+        Command::SetVcp(code, value) => {
+            for mut display in displays((query, needs_caps))? {
+                ensure_capabilities(&mut display, cache, refresh)?;
+                // This sometimes fails but the write still succeeded, ignore the Err for now
+                if let Err(e) = setvcp(&mut display, *code, value) {
+                    warn!("Error while setting feature: {}", e)
+                } else {
+                    writeln!(out, "{}: set 0x{:02x} = {}", display.info.id, code, value)?;
+                }
+                sleep.add(display);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Default control socket path, under the platform runtime (or cache) directory.
+fn default_socket_path() -> Result<PathBuf, Error> {
+    let dirs = ProjectDirs::from("", "", "monitor-switch")
+        .ok_or_else(|| format_err!("Could not determine a runtime directory"))?;
+    let dir = dirs.runtime_dir().unwrap_or_else(|| dirs.cache_dir());
+    std::fs::create_dir_all(dir)?;
+    Ok(dir.join("control.sock"))
+}
+
+/// Handle one control connection: read a single line-framed command, dispatch
+/// it, and write the result back over the same stream.
+fn handle_client(
+    mut stream: LocalSocketStream,
+    query: &Query,
+    needs_caps: bool,
+    cache: Option<&Cache>,
+    refresh: bool,
+    bus_lock: &Mutex<()>,
+) -> Result<(), Error> {
+    let mut line = String::new();
+    {
+        let mut reader = BufReader::new(&mut stream);
+        reader.read_line(&mut line)?;
+    }
+
+    let mut response = Vec::new();
+    match parse_command_line(line.trim()) {
+        Ok(command) => {
+            if let Err(e) = run_command(
+                &command,
+                query,
+                needs_caps,
+                cache,
+                refresh,
+                bus_lock,
+                &mut response,
+            ) {
+                writeln!(response, "error: {}", e)?;
+            }
+        }
+        Err(e) => writeln!(response, "error: {}", e)?,
+    }
+
+    stream.write_all(&response)?;
+    Ok(())
+}
+
+/// Accept control connections on the local socket until the process exits.
+fn serve_socket(
+    path: &Path,
+    query: Arc<Query>,
+    needs_caps: bool,
+    cache: Option<Arc<Cache>>,
+    refresh: bool,
+    bus_lock: Arc<Mutex<()>>,
+) -> Result<(), Error> {
+    // Clean up a socket left behind by a previous run on Unix.
+    #[cfg(unix)]
+    let _ = std::fs::remove_file(path);
+
+    let listener = LocalSocketListener::bind(path)?;
+    info!("Listening for control commands on {}", path.display());
+
+    for conn in listener.incoming() {
+        match conn {
+            Ok(stream) => {
+                if let Err(e) = handle_client(
+                    stream,
+                    &query,
+                    needs_caps,
+                    cache.as_deref(),
+                    refresh,
+                    &bus_lock,
+                ) {
+                    warn!("Control connection error: {}", e)
+                }
+            }
+            Err(e) => warn!("Control socket accept error: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Connect to a running daemon's control socket and forward a single command,
+/// printing whatever the daemon sends back.
+fn control_client(path: &Path, command: &str) -> Result<(), Error> {
+    let mut stream = LocalSocketStream::connect(path)?;
+    writeln!(stream, "{}", command)?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_to_string(&mut response)?;
+    print!("{}", response);
+    Ok(())
+}
+
+/// Input-switching behavior for [`run_daemon`], separated from its bus/cache
+/// plumbing so the function stays under clippy's argument-count lint.
+struct DaemonOptions {
+    input_source: InputValue,
+    interval: Duration,
+    restore: bool,
+}
+
+/// Poll for display hotplug events, applying `options.input_source` to each
+/// newly connected display that matches the query. With `options.restore`, a
+/// display that reappears is returned to the input it was last showing
+/// rather than the configured default. Runs until the process is killed or
+/// enumerating the displays returns an error.
+fn run_daemon(
+    query: &Query,
+    needs_caps: bool,
+    cache: Option<&Cache>,
+    refresh: bool,
+    options: DaemonOptions,
+    bus_lock: &Mutex<()>,
+) -> Result<(), Error> {
+    let DaemonOptions {
+        input_source,
+        interval,
+        restore,
+    } = options;
+    let mut present: HashSet<String> = HashSet::new();
+    let mut last_input: HashMap<String, InputValue> = HashMap::new();
+
+    info!("Watching for display changes every {}s", interval.as_secs());
+    loop {
+        let mut seen = HashSet::new();
+        {
+            // Held for the whole poll so a concurrent control connection can't
+            // interleave DDC/CI transactions on the same bus.
+            let _guard = bus_lock.lock().unwrap();
+            for mut display in displays((query, needs_caps))? {
+                let identity = display_identity(&display);
+                seen.insert(identity.clone());
+
+                if !present.contains(&identity) {
+                    info!("Display connected: {}", identity);
+                    if let Err(e) = ensure_capabilities(&mut display, cache, refresh) {
+                        warn!("Error while reading capabilities: {}", e);
+                        display.handle.sleep();
+                        continue;
+                    }
+                    let target = if restore {
+                        last_input.get(&identity).copied().unwrap_or(input_source)
+                    } else {
+                        input_source
+                    };
+                    // This sometimes fails but the switch still succeeded, ignore the Err for now
+                    if let Err(e) = set_input_source(&mut display, target) {
+                        warn!("Error while setting input: {}", e)
+                    }
+                } else if restore {
+                    // Track the current input so it can be restored after a disconnect.
+                    // Each poll enumerates fresh handles, so capabilities are loaded
+                    // again (from the cache when present) before reading the input.
+                    if let Err(e) = ensure_capabilities(&mut display, cache, false) {
+                        warn!("Error while reading capabilities: {}", e)
+                    } else if let Ok(current) = get_input_source(&mut display) {
+                        last_input.insert(identity.clone(), current);
+                    }
+                }
+
+                display.handle.sleep();
+            }
+        }
+
+        for identity in present.difference(&seen) {
+            info!("Display disconnected: {}", identity);
+        }
+        present = seen;
+
+        thread::sleep(interval);
+    }
+}
+
 fn main() -> Result<(), Error> {
     env_logger::init();
 
@@ -111,6 +764,10 @@ fn main() -> Result<(), Error> {
     let backend_values: Vec<_> = backend_values.iter().map(|v| &v[..]).collect();
 
     let input_source_values: Vec<_> = InputSource::iter_variant_names().collect();
+    let input_source_help = format!(
+        "Input source: a named value ({}) or a raw hex/decimal code such as 0x1b",
+        input_source_values.join(", ")
+    );
 
     let app = App::new("monitor-switch")
         .version(env!("CARGO_PKG_VERSION"))
@@ -150,22 +807,90 @@ fn main() -> Result<(), Error> {
             .takes_value(true)
             .help("Filter by matching serial number")
             // TODO: filter by index? winapi makes things difficult, nothing is identifying...
+        ).arg(Arg::with_name("refresh-cache")
+            .long("refresh-cache")
+            .help("Ignore cached capabilities and re-probe over DDC/CI")
+        ).arg(Arg::with_name("cache-ttl")
+            .long("cache-ttl")
+            .value_name("SECONDS")
+            .takes_value(true)
+            .help("Capability cache lifetime in seconds (default 7 days)")
         ).subcommand(SubCommand::with_name("set")
             .about("Set input source to specified value")
             .arg(Arg::with_name("INPUT")
                  .required(true)
-                 .possible_values(&input_source_values)
+                 .help(&input_source_help)
                  .index(1))
         ).subcommand(SubCommand::with_name("toggle")
             .about("Toggle input source between two values")
             .arg(Arg::with_name("INPUT1")
                  .required(true)
-                 .possible_values(&input_source_values)
+                 .help(&input_source_help)
                  .index(1))
             .arg(Arg::with_name("INPUT2")
                  .required(true)
-                 .possible_values(&input_source_values)
+                 .help(&input_source_help)
                  .index(2))
+        ).subcommand(SubCommand::with_name("cycle")
+            .about("Advance each display to the next input in an ordered list")
+            .arg(Arg::with_name("INPUT")
+                 .required(true)
+                 .multiple(true)
+                 .min_values(2)
+                 .help(&input_source_help)
+                 .index(1))
+            .arg(Arg::with_name("synchronized")
+                 .long("synchronized")
+                 .help("Decide the next input once and apply it to all displays"))
+        ).subcommand(SubCommand::with_name("getvcp")
+            .about("Read any VCP feature value")
+            .arg(Arg::with_name("CODE")
+                 .required(true)
+                 .help("VCP feature code, e.g. 0x10 for brightness")
+                 .index(1))
+        ).subcommand(SubCommand::with_name("setvcp")
+            .about("Write any VCP feature value")
+            .arg(Arg::with_name("CODE")
+                 .required(true)
+                 .help("VCP feature code, e.g. 0x10 for brightness")
+                 .index(1))
+            .arg(Arg::with_name("VALUE")
+                 .required(true)
+                 .help("Raw integer, hex (0x..), or a named value from the MCCS database")
+                 .index(2))
+        ).subcommand(SubCommand::with_name("daemon")
+            .about("Watch for monitor hotplug and reapply the desired input")
+            .arg(Arg::with_name("INPUT")
+                 .required(true)
+                 .help("Input source to apply to newly connected displays (named or raw code)")
+                 .index(1))
+            .arg(Arg::with_name("interval")
+                 .short("t")
+                 .long("interval")
+                 .value_name("SECONDS")
+                 .takes_value(true)
+                 .help("Polling interval in seconds (default 5)"))
+            .arg(Arg::with_name("restore")
+                 .long("restore")
+                 .help("Restore the input a display last showed when it reappears"))
+            .arg(Arg::with_name("socket")
+                 .long("socket")
+                 .value_name("PATH")
+                 .min_values(0)
+                 .max_values(1)
+                 .help("Also listen for control commands on this socket (default path if omitted)"))
+        ).subcommand(SubCommand::with_name("ctl")
+            .about("Forward a command to a running daemon's control socket")
+            .arg(Arg::with_name("socket")
+                 .long("socket")
+                 .value_name("PATH")
+                 .takes_value(true)
+                 .help("Control socket path (defaults to the platform runtime directory, or the cache directory where there is none)"))
+            .arg(Arg::with_name("COMMAND")
+                 .required(true)
+                 .multiple(true)
+                 .help("Command to forward, e.g. `set Hdmi1` or `getvcp 0x10`")
+                 .index(1))
         ).setting(AppSettings::SubcommandRequiredElseHelp);
 
     let matches = app.get_matches();
@@ -198,59 +923,163 @@ fn main() -> Result<(), Error> {
 
     let query = (query, needs_caps);
 
-    let mut sleep = DisplaySleep::default();
+    let refresh_cache = matches.is_present("refresh-cache");
+    let cache_ttl = match matches.value_of("cache-ttl") {
+        Some(v) => Some(v.parse().map_err(|e| format_err!("Invalid --cache-ttl: {}", e))?),
+        None => Some(DEFAULT_CACHE_TTL),
+    };
+    // Opened lazily per-subcommand: `ctl` never touches the cache, and opening
+    // it unconditionally would contend sled's exclusive lock with a daemon.
+    let open_cache = || match Cache::open(cache_ttl) {
+        Ok(cache) => Some(Arc::new(cache)),
+        Err(e) => {
+            warn!("Could not open capability cache, proceeding without it: {}", e);
+            None
+        }
+    };
+
+    let (query, needs_caps) = query;
+    let stdout = io::stdout();
+    let bus_lock = Mutex::new(());
 
     match matches.subcommand() {
         ("set", Some(matches)) => {
-            let input_source: InputSource = matches
+            let input_source: InputValue = matches
                 .value_of("INPUT")
-                .map(InputSource::from_str)
+                .map(InputValue::from_str)
                 .unwrap()?;
-
-            for mut display in displays(query)? {
-                display.update_capabilities()?;
-                // This sometimes fails but the switch still succeeded, ignore the Err for now
-                if let Err(e) = set_input_source(&mut display, input_source) {
-                    warn!("Error while setting input: {}", e)
-                }
-                sleep.add(display);
-            }
+            run_command(
+                &Command::Set(input_source),
+                &query,
+                needs_caps,
+                open_cache().as_deref(),
+                refresh_cache,
+                &bus_lock,
+                &mut stdout.lock(),
+            )?;
         }
         ("toggle", Some(matches)) => {
-            let input_source_1: InputSource = matches
+            let input_source_1: InputValue = matches
                 .value_of("INPUT1")
-                .map(InputSource::from_str)
+                .map(InputValue::from_str)
                 .unwrap()?;
-            let input_source_2: InputSource = matches
+            let input_source_2: InputValue = matches
                 .value_of("INPUT2")
-                .map(InputSource::from_str)
+                .map(InputValue::from_str)
                 .unwrap()?;
+            run_command(
+                &Command::Toggle(input_source_1, input_source_2),
+                &query,
+                needs_caps,
+                open_cache().as_deref(),
+                refresh_cache,
+                &bus_lock,
+                &mut stdout.lock(),
+            )?;
+        }
+        ("cycle", Some(matches)) => {
+            let sources = matches
+                .values_of("INPUT")
+                .unwrap()
+                .map(InputValue::from_str)
+                .collect::<Result<Vec<_>, _>>()?;
+            let synchronized = matches.is_present("synchronized");
+            run_command(
+                &Command::Cycle(sources, synchronized),
+                &query,
+                needs_caps,
+                open_cache().as_deref(),
+                refresh_cache,
+                &bus_lock,
+                &mut stdout.lock(),
+            )?;
+        }
+        ("getvcp", Some(matches)) => {
+            let code = parse_feature_code(matches.value_of("CODE").unwrap())?;
+            run_command(
+                &Command::GetVcp(code),
+                &query,
+                needs_caps,
+                open_cache().as_deref(),
+                refresh_cache,
+                &bus_lock,
+                &mut stdout.lock(),
+            )?;
+        }
+        ("setvcp", Some(matches)) => {
+            let code = parse_feature_code(matches.value_of("CODE").unwrap())?;
+            let value = matches.value_of("VALUE").unwrap().to_owned();
+            run_command(
+                &Command::SetVcp(code, value),
+                &query,
+                needs_caps,
+                open_cache().as_deref(),
+                refresh_cache,
+                &bus_lock,
+                &mut stdout.lock(),
+            )?;
+        }
+        ("daemon", Some(matches)) => {
+            let input_source: InputValue = matches
+                .value_of("INPUT")
+                .map(InputValue::from_str)
+                .unwrap()?;
+            let interval = match matches.value_of("interval") {
+                Some(v) => Duration::from_secs(
+                    v.parse().map_err(|e| format_err!("Invalid --interval: {}", e))?,
+                ),
+                None => Duration::from_secs(5),
+            };
+            let restore = matches.is_present("restore");
+            let query = Arc::new(query);
+            let cache = open_cache();
+            let bus_lock = Arc::new(Mutex::new(()));
 
-            let mut target: Option<InputSource> = None;
-            for mut display in displays(query)? {
-                display.update_capabilities()?;
-
-                if target.is_none() {
-                    let current = get_input_source(&mut display)?;
-
-                    target = if current == input_source_1 {
-                        Some(input_source_2)
-                    } else if current == input_source_2 {
-                        Some(input_source_1)
-                    } else {
-                        bail!(format_err!("Current input source is not a toggle option"))
-                    }
-                }
-
-                if let Some(input_source) = target {
-                    // This sometimes fails but the switch still succeeded, ignore the Err for now
-                    if let Err(e) = set_input_source(&mut display, input_source) {
-                        warn!("Error while setting input: {}", e)
+            if matches.is_present("socket") {
+                let path = match matches.value_of("socket") {
+                    Some(p) => PathBuf::from(p),
+                    None => default_socket_path()?,
+                };
+                let socket_query = Arc::clone(&query);
+                let socket_cache = cache.clone();
+                let socket_bus_lock = Arc::clone(&bus_lock);
+                thread::spawn(move || {
+                    if let Err(e) = serve_socket(
+                        &path,
+                        socket_query,
+                        needs_caps,
+                        socket_cache,
+                        refresh_cache,
+                        socket_bus_lock,
+                    ) {
+                        warn!("Control socket listener stopped: {}", e)
                     }
-                }
-
-                sleep.add(display);
+                });
             }
+
+            run_daemon(
+                &query,
+                needs_caps,
+                cache.as_deref(),
+                refresh_cache,
+                DaemonOptions {
+                    input_source,
+                    interval,
+                    restore,
+                },
+                &bus_lock,
+            )?;
+        }
+        ("ctl", Some(matches)) => {
+            let path = match matches.value_of("socket") {
+                Some(p) => PathBuf::from(p),
+                None => default_socket_path()?,
+            };
+            let command = matches
+                .values_of("COMMAND")
+                .map(|v| v.collect::<Vec<_>>().join(" "))
+                .unwrap_or_default();
+            control_client(&path, &command)?;
         }
         _ => unreachable!("Invalid subcommand"),
     }